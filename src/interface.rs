@@ -1,6 +1,6 @@
 use std::io::{self, Write, BufWriter};
 
-use super::{MatchWithPositions, match_and_score_with_positions};
+use super::{MatchWithPositions, match_and_score_with_positions, sort_best_first};
 use ansi::{clear, color, cursor, style};
 use terminal::{self, Terminal, Key, Event};
 
@@ -120,7 +120,7 @@ impl<'a> Interface<'a> {
             filter_map(|line| match_and_score_with_positions(search, line)).
             collect();
 
-        self.matches.par_sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().reverse());
+        sort_best_first(&mut self.matches, |m| m.1);
     }
 
     // Matches and scores the existing `matches` by `search`, sorting the result
@@ -132,7 +132,7 @@ impl<'a> Interface<'a> {
             filter_map(|&(line, _, _)| match_and_score_with_positions(search, line)).
             collect();
 
-        self.matches.par_sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().reverse());
+        sort_best_first(&mut self.matches, |m| m.1);
     }
 
     // Renders the current state of the Interface to it's `terminal`
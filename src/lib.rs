@@ -1,16 +1,28 @@
 extern crate libc;
 extern crate rayon;
+#[cfg(feature = "unicode-width")]
+extern crate unicode_width;
 
 mod consts;
 mod terminal;
 
 pub mod ansi;
 pub mod stdin;
+pub mod highlight;
 pub mod matcher;
 pub mod matrix;
 pub mod scorer;
 pub mod interface;
 
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rayon::prelude::*;
+use scorer::OrderedScore;
+
 pub type Match<'a> = (&'a str, f64);
 pub type MatchWithPositions<'a> = (&'a str, f64, Vec<usize>);
 
@@ -30,3 +42,652 @@ pub fn match_and_score_with_positions<'a>(needle: &str, haystack: &'a str) -> Op
         None
     }
 }
+
+/// Sorts scored matches best first, keeping matches with equal scores in order
+pub fn sort_best_first<T, F>(matches: &mut [T], score: F)
+    where T: Send, F: Fn(&T) -> f64 + Sync
+{
+    matches.par_sort_by_key(|m| Reverse(OrderedScore(score(m))));
+}
+
+// Scores each item in parallel, returning the indices and scores of the items
+// `score` gives a score to, best first
+fn rank_indices<T, F>(items: &[T], score: F) -> Vec<(usize, f64)>
+    where T: Sync, F: Fn(usize, &T) -> Option<f64> + Sync
+{
+    let mut matches: Vec<_> = items.
+        par_iter().
+        enumerate().
+        filter_map(|(i, item)| score(i, item).map(|score| (i, score))).
+        collect();
+
+    sort_best_first(&mut matches, |m| m.1);
+    matches
+}
+
+/// Scores the needle against each token, returning the best token's index and score
+pub fn score_tokens(needle: &str, tokens: &[&str]) -> Option<(usize, f64)> {
+    tokens.iter().
+        enumerate().
+        filter_map(|(i, token)| match_and_score(needle, token).map(|(_, score)| (i, score))).
+        fold(None, |best, (i, score)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((i, score))
+        })
+}
+
+// How many lines `par_rank_progress` scores between progress reports
+const PROGRESS_CHUNK: usize = 1024;
+
+/// Matches and scores every line in parallel, returning the indices and scores
+/// of the matching lines, best first
+///
+/// `on_progress` is called from the worker threads with the number of lines
+/// processed so far, as each chunk of `PROGRESS_CHUNK` lines completes. Calls
+/// can arrive out of order, but the last chunk to complete always reports
+/// `lines.len()`.
+pub fn par_rank_progress<S, F>(needle: &str, lines: &[S], on_progress: F) -> Vec<(usize, f64)>
+    where S: AsRef<str> + Sync, F: Fn(usize) + Sync
+{
+    let processed = AtomicUsize::new(0);
+
+    let mut matches: Vec<_> = lines.
+        par_chunks(PROGRESS_CHUNK).
+        enumerate().
+        flat_map(|(chunk, lines)| {
+            let offset = chunk * PROGRESS_CHUNK;
+
+            let matches: Vec<_> = lines.iter().
+                enumerate().
+                filter_map(|(i, line)| match_and_score(needle, line.as_ref()).map(|m| (offset + i, m.1))).
+                collect();
+
+            on_progress(processed.fetch_add(lines.len(), Ordering::SeqCst) + lines.len());
+
+            matches
+        }).
+        collect();
+
+    sort_best_first(&mut matches, |m| m.1);
+    matches
+}
+
+/// Returns the score of the `k`th best matching line, or `None` if fewer than
+/// `k` lines match
+///
+/// Only the best `k` scores are held at any time, so the lines aren't sorted.
+pub fn kth_best_score<S: AsRef<str>>(needle: &str, lines: &[S], k: usize) -> Option<f64> {
+    if k == 0 {
+        return None;
+    }
+
+    let mut best = BinaryHeap::with_capacity(k + 1);
+
+    for (_, score) in lines.iter().filter_map(|line| match_and_score(needle, line.as_ref())) {
+        best.push(Reverse(OrderedScore(score)));
+
+        if best.len() > k {
+            best.pop();
+        }
+    }
+
+    if best.len() == k {
+        best.peek().map(|&Reverse(score)| score.into_score())
+    } else {
+        None
+    }
+}
+
+/// Flags whether each line matches the needle, without scoring any of them
+pub fn match_mask<S: AsRef<str> + Sync>(needle: &str, lines: &[S]) -> Vec<bool> {
+    lines.
+        par_iter().
+        map(|line| matcher::matches(needle, line.as_ref())).
+        collect()
+}
+
+/// Matches and scores every line, returning the indices, scores and quality
+/// buckets of the matching lines, best first
+///
+/// `thresholds` should be in descending order. A line lands in the bucket of
+/// the first threshold its score reaches, or in bucket `thresholds.len()` if
+/// it reaches none of them.
+pub fn rank_bucketed<S: AsRef<str> + Sync>(needle: &str, lines: &[S], thresholds: &[f64]) -> Vec<(usize, f64, usize)> {
+    rank_indices(lines, |_, line| match_and_score(needle, line.as_ref()).map(|m| m.1)).
+        into_iter().
+        map(|(i, score)| {
+            let bucket = thresholds.iter().position(|&t| score >= t).unwrap_or(thresholds.len());
+            (i, score, bucket)
+        }).
+        collect()
+}
+
+/// How `merge_rankings` combines the scores of a line ranked more than once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Keep the line's best score
+    MaxScore,
+    /// Add the line's scores together
+    ///
+    /// A `SCORE_MAX` (exact match) makes the sum `SCORE_MAX`, and a
+    /// `SCORE_MIN` adds nothing, so the sum is only `SCORE_MIN` if every
+    /// score is.
+    SumScore
+}
+
+/// Merges several rankings of the same lines into one, best first
+///
+/// Lines are identified by their index; lines with equal merged scores are
+/// ordered by index.
+pub fn merge_rankings(rankings: &[Vec<(usize, f64)>], strategy: MergeStrategy) -> Vec<(usize, f64)> {
+    let mut merged = BTreeMap::new();
+
+    for &(i, score) in rankings.iter().flat_map(|ranking| ranking.iter()) {
+        merged.entry(i).
+            and_modify(|merged: &mut f64| *merged = match strategy {
+                MergeStrategy::MaxScore => merged.max(score),
+                MergeStrategy::SumScore => sum_scores(*merged, score)
+            }).
+            or_insert(score);
+    }
+
+    let mut merged: Vec<_> = merged.into_iter().collect();
+    sort_best_first(&mut merged, |m| m.1);
+    merged
+}
+
+// Adds two scores for `MergeStrategy::SumScore`, without letting the
+// infinite sentinels make a NaN
+fn sum_scores(a: f64, b: f64) -> f64 {
+    if a == consts::SCORE_MAX || b == consts::SCORE_MAX {
+        consts::SCORE_MAX
+    } else if a == consts::SCORE_MIN {
+        b
+    } else if b == consts::SCORE_MIN {
+        a
+    } else {
+        a + b
+    }
+}
+
+/// Matches each of the needle's segments against a different `/`-delimited
+/// segment of the haystack, in any order
+///
+/// Each needle segment, in turn, takes whichever unclaimed haystack segment it
+/// scores best against. The result is the sum of those scores, with positions
+/// indexing chars of the whole haystack, or `None` if a needle segment finds
+/// no haystack segment to match.
+///
+/// Empty needle segments, as left by splitting `"user/"` on `/`, are skipped.
+/// Returns `None` if no segments are left.
+pub fn score_segments_unordered(needle_segments: &[&str], haystack: &str) -> Option<(f64, Vec<usize>)> {
+    let needle_segments: Vec<&str> = needle_segments.iter().cloned().filter(|segment| !segment.is_empty()).collect();
+
+    if needle_segments.is_empty() {
+        return None;
+    }
+
+    let mut segments = vec![];
+    let mut offset = 0;
+
+    for segment in haystack.split('/') {
+        segments.push((offset, segment));
+        offset += segment.chars().count() + 1;
+    }
+
+    claim_segments(&needle_segments, &segments)
+}
+
+/// Matches each of the needle's whitespace-separated words against a
+/// different word of the haystack, in any order
+///
+/// Words are claimed as in `score_segments_unordered`, so "fox red" matches
+/// "The Red Arctic Fox" as well as "red fox" does. Returns `None` if the needle
+/// has no words or one of them finds no haystack word to match.
+pub fn score_words(needle: &str, haystack: &str) -> Option<(f64, Vec<usize>)> {
+    let needle_words: Vec<_> = needle.split_whitespace().collect();
+
+    if needle_words.is_empty() {
+        return None;
+    }
+
+    let mut words = vec![];
+    let mut word_start = None;
+
+    for (i, (offset, ch)) in haystack.char_indices().enumerate() {
+        match word_start {
+            None if !ch.is_whitespace() => word_start = Some((i, offset)),
+            Some((start, start_offset)) if ch.is_whitespace() => {
+                words.push((start, &haystack[start_offset..offset]));
+                word_start = None;
+            },
+            _ => {}
+        }
+    }
+
+    if let Some((start, start_offset)) = word_start {
+        words.push((start, &haystack[start_offset..]));
+    }
+
+    claim_segments(&needle_words, &words)
+}
+
+// Lets each needle, in turn, claim the unclaimed segment it scores best
+// against, given each segment's char offset in the haystack
+fn claim_segments(needles: &[&str], segments: &[(usize, &str)]) -> Option<(f64, Vec<usize>)> {
+    let mut claimed = vec![false; segments.len()];
+    let mut total = 0.0;
+    let mut positions = vec![];
+
+    for needle in needles {
+        let (best, (_, score, segment_positions)) = segments.iter().
+            enumerate().
+            filter(|&(i, _)| !claimed[i]).
+            filter_map(|(i, &(_, segment))| match_and_score_with_positions(needle, segment).map(|m| (i, m))).
+            fold(None, |best, (i, m)| match best {
+                Some((_, (_, best_score, _))) if best_score >= m.1 => best,
+                _ => Some((i, m))
+            })?;
+
+        claimed[best] = true;
+        total += score;
+        positions.extend(segment_positions.into_iter().map(|p| segments[best].0 + p));
+    }
+
+    positions.sort();
+    Some((total, positions))
+}
+
+/// Matches and scores the needle against the file stem of each path, keeping
+/// only paths with the extension `ext` (with or without its leading `.`),
+/// best first
+///
+/// An empty `ext` keeps the paths without an extension. The extension is what
+/// follows the basename's last `.`, so dotfiles such as `.bashrc` and names
+/// ending in a `.` have none, and their whole basename is the stem.
+pub fn rank_with_ext<S: AsRef<str> + Sync>(needle: &str, ext: &str, paths: &[S]) -> Vec<(usize, f64)> {
+    let ext = ext.trim_start_matches('.');
+
+    rank_indices(paths, |_, path| {
+        let basename = path.as_ref().rsplit('/').next().unwrap_or("");
+
+        let (stem, path_ext) = match basename.rfind('.') {
+            Some(dot) if dot > 0 && dot + 1 < basename.len() => (&basename[..dot], &basename[dot + 1..]),
+            _ => (basename, "")
+        };
+
+        if path_ext == ext {
+            match_and_score(needle, stem).map(|m| m.1)
+        } else {
+            None
+        }
+    })
+}
+
+/// Matches and scores every line in parallel, returning the indices, scores
+/// and match positions of the best `k` matching lines, best first
+///
+/// Positions are only computed for those `k` lines.
+pub fn par_rank_with_positions<S: AsRef<str> + Sync>(needle: &str, lines: &[S], k: usize) -> Vec<(usize, f64, Vec<usize>)> {
+    let mut matches = rank_indices(lines, |_, line| match_and_score(needle, line.as_ref()).map(|m| m.1));
+    matches.truncate(k);
+
+    matches.
+        into_par_iter().
+        map(|(i, score)| {
+            let (_, positions) = scorer::score_with_positions(needle, lines[i].as_ref());
+            (i, score, positions)
+        }).
+        collect()
+}
+
+/// Matches and scores each distinct line once, returning the index of its
+/// first occurrence and its score, best first
+pub fn rank_dedup<S: AsRef<str> + Sync>(needle: &str, lines: &[S]) -> Vec<(usize, f64)> {
+    let mut seen = HashSet::with_capacity(lines.len());
+    let first: Vec<bool> = lines.iter().map(|line| seen.insert(line.as_ref())).collect();
+
+    rank_indices(lines, |i, line| if first[i] { match_and_score(needle, line.as_ref()).map(|m| m.1) } else { None })
+}
+
+/// Matches and scores every line, then ranks the matching lines by the value
+/// `adjust` gives each index and score, best first
+///
+/// `adjust` is only called for matching lines; the values it returns take
+/// the scores' place in the result.
+pub fn rank_by<S, F>(needle: &str, lines: &[S], adjust: F) -> Vec<(usize, f64)>
+    where S: AsRef<str> + Sync, F: Fn(usize, f64) -> f64 + Sync
+{
+    rank_indices(lines, |i, line| match_and_score(needle, line.as_ref()).map(|m| adjust(i, m.1)))
+}
+
+/// Interned strings, each stored once and referred to by a `u32` handle
+#[derive(Debug, Default)]
+pub struct StringPool {
+    strings: Vec<Box<str>>,
+    // the handles of the strings interned with each hash
+    by_hash: HashMap<u64, Vec<u32>>
+}
+
+impl StringPool {
+    pub fn new() -> StringPool {
+        StringPool::default()
+    }
+
+    /// Returns the handle of `string`, adding it to the pool if it's new
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool already holds `u32::MAX + 1` strings.
+    pub fn intern(&mut self, string: &str) -> u32 {
+        self.intern_hashed(string, hash_str(string))
+    }
+
+    // Interns `string` as if it hashed to `hash`, so tests can force collisions
+    fn intern_hashed(&mut self, string: &str, hash: u64) -> u32 {
+        let strings = &mut self.strings;
+        let handles = self.by_hash.entry(hash).or_default();
+
+        if let Some(&handle) = handles.iter().find(|&&handle| &*strings[handle as usize] == string) {
+            return handle;
+        }
+
+        let handle = u32::try_from(strings.len()).expect("StringPool is out of handles");
+        strings.push(string.into());
+        handles.push(handle);
+
+        handle
+    }
+
+    /// Returns the string a handle refers to, or `None` if the handle isn't
+    /// from this pool
+    pub fn get(&self, handle: u32) -> Option<&str> {
+        self.strings.get(handle as usize).map(|string| &**string)
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+fn hash_str(string: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    string.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Matches and scores the pooled strings that `handles` refer to, returning
+/// the handles and scores of the matching strings, best first
+///
+/// # Panics
+///
+/// Panics if a handle isn't from `pool`.
+pub fn rank_interned(needle: &str, pool: &StringPool, handles: &[u32]) -> Vec<(u32, f64)> {
+    let matches = rank_indices(handles, |_, &handle| {
+        let string = pool.get(handle).expect("handle not from this pool");
+        match_and_score(needle, string).map(|m| m.1)
+    });
+
+    matches.into_iter().map(|(i, score)| (handles[i], score)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ranks the matching lines one at a time, for checking the rankers against
+    fn sequential_rank<S: AsRef<str>>(needle: &str, lines: &[S]) -> Vec<(usize, f64)> {
+        let mut ranked: Vec<_> = lines.iter().
+            enumerate().
+            filter_map(|(i, line)| match_and_score(needle, line.as_ref()).map(|m| (i, m.1))).
+            collect();
+
+        ranked.sort_by_key(|m| Reverse(OrderedScore(m.1)));
+        ranked
+    }
+
+    #[test]
+    fn test_score_tokens() {
+        let tags = ["rust", "cli", "fuzzy"];
+
+        assert_eq!(score_tokens("fuz", &tags).map(|(i, _)| i), Some(2));
+        assert_eq!(score_tokens("fuz", &tags).map(|(_, score)| score), Some(scorer::score("fuz", "fuzzy")));
+
+        // the better of two matching tokens wins
+        assert_eq!(score_tokens("ru", &["rerun", "rust"]).map(|(i, _)| i), Some(1));
+
+        assert_eq!(score_tokens("xyz", &tags), None);
+        assert_eq!(score_tokens("fuz", &[]), None);
+    }
+
+    #[test]
+    fn test_kth_best_score() {
+        let lines = ["app/models/order.rb", "spec/models/order_spec.rb", "other_garbage.rb",
+                     "Gemfile", "node_modules/test/a/thing.js", "vendor/bundle/ruby/gem.rb"];
+
+        let ranked = sequential_rank("or", &lines);
+        assert_eq!(ranked.len(), 4);
+
+        for k in 1..5 {
+            assert_eq!(kth_best_score("or", &lines, k), Some(ranked[k - 1].1));
+        }
+
+        assert_eq!(kth_best_score("or", &lines, 5), None);
+        assert_eq!(kth_best_score("or", &lines, 0), None);
+        assert_eq!(kth_best_score::<&str>("or", &[], 1), None);
+    }
+
+    #[test]
+    fn test_match_mask() {
+        let lines = ["app/models/order.rb", "spec/models/order_spec.rb", "other_garbage.rb",
+                     "Gemfile", "node_modules/test/a/thing.js", "vendor/bundle/ruby/gem.rb", ""];
+
+        for needle in &["amor", "or", "", "gem", "x"] {
+            let expected: Vec<bool> = lines.iter().map(|line| match_and_score(needle, line).is_some()).collect();
+            assert_eq!(match_mask(needle, &lines), expected);
+        }
+
+        assert_eq!(match_mask("or", &lines), vec![true, true, true, false, false, true, false]);
+    }
+
+    #[test]
+    fn test_rank_bucketed() {
+        let lines = ["app/models/order", "app/models/zrder", "a/m/o/r", "amor", "garbage"];
+
+        let ranked = rank_bucketed("amor", &lines, &[consts::SCORE_MAX, 3.0]);
+
+        assert_eq!(ranked.iter().map(|m| (m.0, m.2)).collect::<Vec<_>>(), vec![(3, 0), (0, 1), (2, 1), (1, 2)]);
+        assert_eq!(ranked[0].1, consts::SCORE_MAX);
+        assert!(ranked[2].1 >= 3.0 && ranked[3].1 < 3.0);
+
+        // without thresholds, everything shares one bucket
+        assert!(rank_bucketed("amor", &lines, &[]).iter().all(|m| m.2 == 0));
+    }
+
+    #[test]
+    fn test_merge_rankings() {
+        let fuzzy = vec![(2, 3.5), (0, 1.0), (4, 0.5)];
+        let exact = vec![(0, 3.0), (1, 2.0)];
+        let rankings = [fuzzy, exact];
+
+        assert_eq!(merge_rankings(&rankings, MergeStrategy::MaxScore), vec![(2, 3.5), (0, 3.0), (1, 2.0), (4, 0.5)]);
+        assert_eq!(merge_rankings(&rankings, MergeStrategy::SumScore), vec![(0, 4.0), (2, 3.5), (1, 2.0), (4, 0.5)]);
+
+        assert_eq!(merge_rankings(&[vec![(1, 1.0)], vec![(0, 1.0)]], MergeStrategy::MaxScore), vec![(0, 1.0), (1, 1.0)]);
+        assert_eq!(merge_rankings(&[], MergeStrategy::SumScore), vec![]);
+
+        // an exact match wins the sum, and a non-match adds nothing
+        let (max, min) = (consts::SCORE_MAX, consts::SCORE_MIN);
+        let rankings = [vec![(0, max), (1, min), (2, 1.0), (3, min)], vec![(0, min), (1, 2.0), (2, max), (3, min)], vec![(1, 0.5)]];
+        assert_eq!(merge_rankings(&rankings, MergeStrategy::SumScore), vec![(0, max), (2, max), (1, 2.5), (3, min)]);
+        assert_eq!(merge_rankings(&rankings, MergeStrategy::MaxScore), vec![(0, max), (2, max), (1, 2.0), (3, min)]);
+    }
+
+    #[test]
+    fn test_score_segments_unordered() {
+        assert!(match_and_score("user/models", "src/models/user").is_none());
+
+        let (score, positions) = score_segments_unordered(&["user", "models"], "src/models/user").unwrap();
+        assert_eq!(score, consts::SCORE_MAX);
+        assert_eq!(positions, vec![4, 5, 6, 7, 8, 9, 11, 12, 13, 14]);
+
+        let (score, positions) = score_segments_unordered(&["usr", "mod"], "src/models/user.rs").unwrap();
+        assert_eq!(score, scorer::score("usr", "user.rs") + scorer::score("mod", "models"));
+        assert_eq!(positions, vec![4, 5, 6, 11, 12, 16]);
+
+        // each haystack segment is only matched once
+        assert_eq!(score_segments_unordered(&["mo", "mo"], "src/models/user"), None);
+        assert_eq!(score_segments_unordered(&["xyz"], "src/models/user"), None);
+
+        // empty needle segments don't claim a haystack segment
+        assert_eq!(score_segments_unordered(&["user", ""], "src/models/user"), score_segments_unordered(&["user"], "src/models/user"));
+        assert_eq!(score_segments_unordered(&["usr", ""], "src/models/user"), score_segments_unordered(&["usr"], "src/models/user"));
+        assert_eq!(score_segments_unordered(&["", "", "", "user"], "src/user"), score_segments_unordered(&["user"], "src/user"));
+        assert_eq!(score_segments_unordered(&[""], "src/models/user"), None);
+        assert_eq!(score_segments_unordered(&[], "src/models/user"), None);
+    }
+
+    #[test]
+    fn test_rank_with_ext() {
+        let paths = ["src/main.rs", "src/main.py", "src/domain/mail.rs", "main.rs/lib.py", "src/.rs", "src/rs"];
+
+        let ranked = rank_with_ext("mai", "rs", &paths);
+        assert_eq!(ranked.iter().map(|m| m.0).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(ranked[0].1, scorer::score("mai", "main"));
+
+        assert_eq!(rank_with_ext("mai", ".py", &paths).iter().map(|m| m.0).collect::<Vec<_>>(), vec![1]);
+        assert!(rank_with_ext("", "rs", &paths).iter().map(|m| m.0).eq(vec![0, 2]));
+        assert!(rank_with_ext("mai", "js", &paths).is_empty());
+
+        // an empty extension keeps the paths without one
+        let paths = ["a/foo", "a/foo.", "a/.foo", "a/foo.rs", "a/.foo.rs"];
+        assert_eq!(rank_with_ext("foo", "", &paths).iter().map(|m| m.0).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(rank_with_ext("foo", ".", &paths).iter().map(|m| m.0).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(rank_with_ext("foo", "rs", &paths).iter().map(|m| m.0).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(rank_with_ext("foo", "", &paths)[0].1, scorer::score("foo", "foo"));
+    }
+
+    #[test]
+    fn test_par_rank_with_positions() {
+        let lines: Vec<_> = (0..2000).map(|i| format!("app/models/{}/order_{}.rb", i % 7, i)).collect();
+
+        let expected: Vec<_> = sequential_rank("am3or", &lines).
+            into_iter().
+            take(10).
+            map(|(i, score)| (i, score, scorer::score_with_positions("am3or", &lines[i]).1)).
+            collect();
+
+        let ranked = par_rank_with_positions("am3or", &lines, 10);
+        assert_eq!(ranked.len(), 10);
+        assert_eq!(ranked, expected);
+
+        assert_eq!(par_rank_with_positions("am3or", &lines, 0), vec![]);
+        assert_eq!(par_rank_with_positions("xyz", &lines, 10), vec![]);
+    }
+
+    #[test]
+    fn test_par_rank_progress() {
+        use std::sync::Mutex;
+
+        let lines: Vec<_> = (0..5000).map(|i| format!("app/models/{}/order.rb", i)).collect();
+        let reports = Mutex::new(vec![]);
+
+        let ranked = par_rank_progress("amo1r", &lines, |n| reports.lock().unwrap().push(n));
+
+        let reports = reports.into_inner().unwrap();
+        assert_eq!(reports.len(), 5);
+        assert_eq!(reports.iter().max(), Some(&lines.len()));
+
+        assert!(!ranked.is_empty());
+        assert_eq!(ranked, sequential_rank("amo1r", &lines));
+    }
+
+    #[test]
+    fn test_rank_dedup() {
+        let lines = ["src/main.rs", "src/lib.rs", "src/main.rs", "README.md", "src/lib.rs", "src/main.rs"];
+
+        let ranked = rank_dedup("src", &lines);
+        let mut indices: Vec<_> = ranked.iter().map(|m| m.0).collect();
+        indices.sort();
+        assert_eq!(indices, vec![0, 1]);
+        assert_eq!(ranked.iter().find(|m| m.0 == 0).map(|m| m.1), Some(scorer::score("src", "src/main.rs")));
+
+        assert_eq!(rank_dedup("md", &lines), vec![(3, scorer::score("md", "README.md"))]);
+        assert!(rank_dedup("xyz", &lines).is_empty());
+        assert!(rank_dedup::<&str>("src", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_rank_by() {
+        use std::sync::Mutex;
+
+        let lines = ["app/models/order.rb", "spec/models/order_spec.rb", "README.md", "app/models/old/order.rb"];
+        let ranked: Vec<_> = rank_by("mor", &lines, |_, score| score).iter().map(|m| m.0).collect();
+        assert_eq!(ranked, vec![0, 1, 3]);
+
+        // a recency factor favouring later lines reorders the matches
+        let recency = [0.1, 0.5, 1.0, 1.0];
+        let ranked = rank_by("mor", &lines, |i, score| score * recency[i]);
+        assert_eq!(ranked.iter().map(|m| m.0).collect::<Vec<_>>(), vec![3, 1, 0]);
+        assert_eq!(ranked[0].1, scorer::score("mor", lines[3]));
+
+        let adjusted = Mutex::new(vec![]);
+        rank_by("mor", &lines, |i, score| { adjusted.lock().unwrap().push(i); score });
+        let mut adjusted = adjusted.into_inner().unwrap();
+        adjusted.sort();
+        assert_eq!(adjusted, vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn test_score_words() {
+        let (score, positions) = score_words("red fox", "The Red Arctic Fox").unwrap();
+        assert_eq!(positions, vec![4, 5, 6, 15, 16, 17]);
+        assert_eq!(score, scorer::score("red", "Red") + scorer::score("fox", "Fox"));
+
+        // word order isn't enforced
+        assert_eq!(score_words("fox red", "The Red Arctic Fox"), Some((score, positions)));
+        assert_eq!(score_words("  arc\tfx ", "The Red\tArctic  Fox").map(|m| m.1), Some(vec![8, 9, 10, 16, 18]));
+
+        // each haystack word matches one needle word at most
+        assert_eq!(score_words("red red", "The Red Arctic Fox"), None);
+        assert_eq!(score_words("redfox", "The Red Arctic Fox"), None);
+        assert_eq!(score_words("", "The Red Arctic Fox"), None);
+        assert_eq!(score_words("red", ""), None);
+    }
+
+    #[test]
+    fn test_rank_interned() {
+        let lines = ["app/models/order.rb", "spec/models/order_spec.rb", "other_garbage.rb",
+                     "app/models/order.rb", "vendor/bundle/ruby/gem.rb", "app/models/old/order.rb"];
+
+        let mut pool = StringPool::new();
+        let handles: Vec<_> = lines.iter().map(|line| pool.intern(line)).collect();
+        assert_eq!(handles, vec![0, 1, 2, 0, 3, 4]);
+        assert_eq!(pool.len(), 5);
+        assert_eq!(pool.get(4), Some("app/models/old/order.rb"));
+        assert_eq!(pool.get(5), None);
+
+        let expected: Vec<_> = sequential_rank("mor", &lines).into_iter().map(|(i, score)| (handles[i], score)).collect();
+
+        assert_eq!(rank_interned("mor", &pool, &handles), expected);
+        assert!(rank_interned("xyz", &pool, &handles).is_empty());
+        assert!(StringPool::new().is_empty());
+    }
+
+    #[test]
+    fn test_string_pool_collisions() {
+        let mut pool = StringPool::new();
+
+        // strings sharing a hash still get their own handles
+        let a = pool.intern_hashed("a", 0);
+        let b = pool.intern_hashed("b", 0);
+        assert_ne!(a, b);
+        assert_eq!(pool.intern_hashed("b", 0), b);
+        assert_eq!(pool.intern_hashed("a", 0), a);
+        assert_eq!((pool.get(b), pool.len()), (Some("b"), 2));
+    }
+}
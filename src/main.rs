@@ -3,7 +3,7 @@ extern crate clap;
 extern crate rayon;
 
 use std::io::{self, Write, BufWriter};
-use rff::{stdin, match_and_score};
+use rff::{stdin, match_and_score, sort_best_first};
 use rff::interface::{Interface, Error};
 use clap::{App, Arg};
 use rayon::prelude::*;
@@ -60,11 +60,12 @@ fn benchmark(needle: &str) {
 
     // in benchmark mode, we run the match/score/sort loop 100 times
     for _ in 0..100 {
-        lines
+        let mut matches: Vec<_> = lines
             .par_iter()
             .filter_map(|line| match_and_score(needle, line))
-            .collect::<Vec<_>>()
-            .par_sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().reverse());
+            .collect();
+
+        sort_best_first(&mut matches, |m| m.1);
     }
 }
 
@@ -75,7 +76,7 @@ fn search(needle: &str) {
         .filter_map(|line| match_and_score(needle, line))
         .collect();
 
-    lines.par_sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().reverse());
+    sort_best_first(&mut lines, |m| m.1);
 
     let stdout = io::stdout();
     let mut stdout = BufWriter::new(stdout.lock());
@@ -1,5 +1,3 @@
-#[allow(unused_imports)] use std::ascii::AsciiExt;
-
 /// Searches for needle's chars in the haystack
 ///
 /// # Examples
@@ -20,13 +18,94 @@ pub fn matches(needle: &str, haystack: &str) -> bool {
     })
 }
 
+/// Lists the needle's chars (and their indices) that the greedy walk used by
+/// `matches` couldn't place in the haystack
+///
+/// A stalled char is skipped, and the walk resumes with the next needle char
+/// from where it left off.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(rff::matcher::unmatched_needle_chars("axz", "abc"), vec![(1, 'x'), (2, 'z')]);
+/// ```
+pub fn unmatched_needle_chars(needle: &str, haystack: &str) -> Vec<(usize, char)> {
+    let mut hchars = haystack.chars();
+
+    needle.chars().enumerate().filter(|&(_, n)| {
+        let mut rest = hchars.clone();
+
+        if rest.any(|h| eq(n, h)) {
+            hchars = rest;
+            false
+        } else {
+            true
+        }
+    }).collect()
+}
+
 /// Compares two characters case-insensitively
+///
+/// With the `fullwidth` feature, full-width forms compare equal to their
+/// half-width equivalents. With the `punctuation` feature, Unicode dashes and
+/// quotes compare equal to their ASCII counterparts.
 #[inline(always)]
 pub fn eq(a: char, b: char) -> bool {
-    match a {
-        _ if a == b => true,
-        _ if a.is_ascii() || b.is_ascii() => a.eq_ignore_ascii_case(&b),
-        _ => a.to_lowercase().eq(b.to_lowercase())
+    let (a, b) = (fold(a), fold(b));
+
+    if a.is_ascii() || b.is_ascii() {
+        eq_ascii(a, b)
+    } else {
+        a == b || a.to_lowercase().eq(b.to_lowercase())
+    }
+}
+
+// Compares two chars, at least one of them ASCII, ignoring ASCII case
+//
+// Only ASCII letters differ from their other case by the 0x20 bit alone, and
+// a non-ASCII char always differs from an ASCII one in a higher bit.
+#[inline(always)]
+fn eq_ascii(a: char, b: char) -> bool {
+    let (a, b) = (a as u32, b as u32);
+    let is_letter = (a | 0x20).wrapping_sub('a' as u32) < 26;
+
+    (a == b) | ((a ^ b == 0x20) & is_letter)
+}
+
+/// Applies the char foldings enabled by features, so that haystack chars are
+/// matched and scored the same way
+#[inline(always)]
+pub(crate) fn fold(ch: char) -> char {
+    #[cfg(feature = "fullwidth")]
+    let ch = fold_width(ch);
+
+    #[cfg(feature = "punctuation")]
+    let ch = fold_punctuation(ch);
+
+    ch
+}
+
+/// Maps full-width ASCII variants (U+FF01 to U+FF5E) and the ideographic
+/// space to their half-width forms
+#[cfg(feature = "fullwidth")]
+#[inline(always)]
+fn fold_width(ch: char) -> char {
+    match ch {
+        '\u{FF01}' ..= '\u{FF5E}' => ::std::char::from_u32(ch as u32 - 0xFEE0).unwrap_or(ch),
+        '\u{3000}' => ' ',
+        _ => ch
+    }
+}
+
+/// Maps Unicode dash and quote variants to their ASCII forms
+#[cfg(feature = "punctuation")]
+#[inline(always)]
+fn fold_punctuation(ch: char) -> char {
+    match ch {
+        '\u{2010}' ..= '\u{2015}' | '\u{2212}' | '\u{FE58}' | '\u{FE63}' => '-',
+        '\u{2018}' ..= '\u{201B}' | '\u{2032}' => '\'',
+        '\u{201C}' ..= '\u{201F}' | '\u{2033}' => '"',
+        _ => ch
     }
 }
 
@@ -41,6 +120,34 @@ mod tests {
         assert!(!eq('a', 'b'));
     }
 
+    #[test]
+    fn test_eq_ascii() {
+        // the comparison `eq` used before its ASCII fast path
+        fn reference(a: char, b: char) -> bool {
+            match a {
+                _ if a == b => true,
+                _ if a.is_ascii() || b.is_ascii() => a.eq_ignore_ascii_case(&b),
+                _ => a.to_lowercase().eq(b.to_lowercase())
+            }
+        }
+
+        let chars: Vec<char> = (0..0x250).
+            filter_map(::std::char::from_u32).
+            chain("ẞßİıΣσςKkÅåДд山💣".chars()).
+            collect();
+
+        for &a in &chars {
+            for &b in &chars {
+                assert_eq!(eq(a, b), reference(a, b), "eq({:?}, {:?})", a, b);
+            }
+        }
+
+        assert!(eq('Z', 'z'));
+        assert!(!eq('@', '`'));
+        assert!(!eq('[', '{'));
+        assert!(!eq('k', '\u{212A}'));
+    }
+
     #[test]
     fn test_matches() {
         assert!(matches("a", "a"));
@@ -68,4 +175,44 @@ mod tests {
         assert!(matches("weiß", "WEIẞ"));
         assert!(matches("хди́ь", "ХОДИ́ТЬ"));
     }
+
+    #[test]
+    #[cfg(feature = "fullwidth")]
+    fn test_fullwidth() {
+        assert!(eq('a', 'ａ'));
+        assert!(eq('A', 'ａ'));
+        assert!(eq('/', '／'));
+        assert!(eq(' ', '\u{3000}'));
+        assert!(!eq('a', 'ｂ'));
+
+        assert!(matches("foo", "ｆｏｏ"));
+        assert!(matches("src/main", "ｓｒｃ／ｍａｉｎ.rs"));
+        assert!(!matches("bar", "ｆｏｏ"));
+    }
+
+    #[test]
+    #[cfg(feature = "punctuation")]
+    fn test_punctuation() {
+        assert!(eq('-', '–'));
+        assert!(eq('-', '—'));
+        assert!(eq('-', '−'));
+        assert!(eq('\'', '’'));
+        assert!(eq('"', '“'));
+        assert!(eq('–', '—'));
+        assert!(!eq('-', '’'));
+
+        assert!(matches("well-known", "The Well–Known Title"));
+        assert!(matches("don't", "Don’t Stop"));
+        assert!(!matches("a-b", "a_b"));
+    }
+
+    #[test]
+    fn test_unmatched_needle_chars() {
+        assert_eq!(unmatched_needle_chars("xyz", "abc"), vec![(0, 'x'), (1, 'y'), (2, 'z')]);
+        assert_eq!(unmatched_needle_chars("axz", "abc"), vec![(1, 'x'), (2, 'z')]);
+        assert_eq!(unmatched_needle_chars("acb", "abc"), vec![(2, 'b')]);
+
+        assert!(unmatched_needle_chars("amor", "app/models/order").is_empty());
+        assert!(unmatched_needle_chars("", "abc").is_empty());
+    }
 }
@@ -3,13 +3,129 @@
 // Licensed under the MIT license
 // https://github.com/jhawthorn/fzy
 
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+
 use consts::*;
-use matcher::eq;
+use matcher::{eq, fold, matches};
 use matrix::Matrix;
 
 pub fn score(needle: &str, haystack: &str) -> f64 {
+    score_with_lengths(needle, haystack, needle.chars().count(), haystack.chars().count())
+}
+
+/// Scores the needle like `score`, given the char counts of the needle and
+/// haystack, for callers that already know them
+pub fn score_with_lengths(needle: &str, haystack: &str, needle_length: usize, haystack_length: usize) -> f64 {
+    debug_assert_eq!(needle_length, needle.chars().count());
+    debug_assert_eq!(haystack_length, haystack.chars().count());
+
+    // empty needle or haystack
+    if needle_length == 0 || haystack_length == 0 {
+        return SCORE_MIN;
+    }
+
+    // perfect match
+    if needle_length == haystack_length {
+        return SCORE_MAX;
+    }
+
+    // unreasonably large haystack
+    if haystack_length > 1024 {
+        return SCORE_MIN;
+    }
+
+    let (_, m) = calculate_score(needle, needle_length, haystack.chars(), haystack_length, &Unconstrained);
+
+    m.get(needle_length - 1, haystack_length - 1)
+}
+
+/// Scores the needle like `score` for each haystack column its last char
+/// could be matched at, counting the trailing gap after that column
+///
+/// Entries are negative infinity where no alignment can end, so all of them
+/// are if the needle doesn't match. Otherwise the highest one is the needle's
+/// `score`.
+///
+/// # Examples
+///
+/// ```
+/// use rff::scorer::{last_row_scores, score};
+///
+/// let row = last_row_scores("foo", "foofoo");
+/// assert_eq!(row[2], score("foo", "foofoo"));
+/// assert_eq!(row[3], f64::NEG_INFINITY);
+/// ```
+pub fn last_row_scores(needle: &str, haystack: &str) -> Vec<f64> {
+    let needle_length = needle.chars().count();
+    let haystack_length = haystack.chars().count();
+
+    // empty or non-matching needle, or unreasonably large haystack
+    if needle_length == 0 || haystack_length > 1024 || !matches(needle, haystack) {
+        return vec![SCORE_MIN; haystack_length];
+    }
+
+    // perfect match
+    if needle_length == haystack_length {
+        let mut row = vec![SCORE_MIN; haystack_length];
+        row[haystack_length - 1] = SCORE_MAX;
+        return row;
+    }
+
+    let (d, _) = calculate_score(needle, needle_length, haystack.chars(), haystack_length, &Unconstrained);
+
+    // add the trailing gap one char at a time, exactly as the last row of `m`
+    // does; rounding is monotonic, so taking the best entry afterwards gives
+    // bit-for-bit the score
+    (0..haystack_length).map(|j| {
+        (j + 1..haystack_length).fold(d.get(needle_length - 1, j), |score, _| score + SCORE_GAP_TRAILING)
+    }).collect()
+}
+
+/// Returns the positions of the best alignment of the needle whose last char
+/// is matched at column `j_end`, such as an endpoint found with
+/// `last_row_scores`
+///
+/// Returns no positions if no alignment ends at `j_end`.
+///
+/// # Examples
+///
+/// ```
+/// use rff::scorer::positions_ending_at;
+///
+/// assert_eq!(positions_ending_at("foo", "foofoo", 5), vec![3, 4, 5]);
+/// assert_eq!(positions_ending_at("foo", "foofoo", 2), vec![0, 1, 2]);
+/// ```
+pub fn positions_ending_at(needle: &str, haystack: &str, j_end: usize) -> Vec<usize> {
+    let needle_length = needle.chars().count();
+    let haystack_length = haystack.chars().count();
+
+    // empty needle, column outside the haystack, or unreasonably large haystack
+    if needle_length == 0 || j_end >= haystack_length || haystack_length > 1024 {
+        return vec![];
+    }
+
+    let (d, m) = calculate_score(needle, needle_length, haystack.chars(), haystack_length, &Unconstrained);
+
+    if d.get(needle_length - 1, j_end) == SCORE_MIN {
+        return vec![];
+    }
+
+    derive_positions_from(&d, &m, needle_length, j_end, true)
+}
+
+/// Scores the needle like `score`, scaling each needle char's match and
+/// bonus contribution by the weight at the same index
+///
+/// # Panics
+///
+/// Panics if `weights` doesn't have exactly one entry per needle char.
+pub fn score_weighted(needle: &str, weights: &[f64], haystack: &str) -> f64 {
     let needle_length = needle.chars().count();
 
+    assert_eq!(weights.len(), needle_length, "expected one weight per needle char");
+
     // empty needle
     if needle_length == 0 {
         return SCORE_MIN;
@@ -17,6 +133,11 @@ pub fn score(needle: &str, haystack: &str) -> f64 {
 
     let haystack_length = haystack.chars().count();
 
+    // empty haystack
+    if haystack_length == 0 {
+        return SCORE_MIN;
+    }
+
     // perfect match
     if needle_length == haystack_length {
         return SCORE_MAX;
@@ -27,12 +148,67 @@ pub fn score(needle: &str, haystack: &str) -> f64 {
         return SCORE_MIN;
     }
 
-    let (_, m) = calculate_score(needle, needle_length, haystack, haystack_length);
+    let constraints = CharConstraints { weights: Some(weights), ..CharConstraints::default() };
+    let (_, m) = calculate_score(needle, needle_length, haystack.chars(), haystack_length, &constraints);
 
     m.get(needle_length - 1, haystack_length - 1)
 }
 
+/// Scores the needle like `score`, along with the char index each needle
+/// char was matched at
+///
+/// Positions are in logical order, the order chars are stored in, even where
+/// right-to-left text is displayed in a different order.
 pub fn score_with_positions(needle: &str, haystack: &str) -> (f64, Vec<usize>) {
+    score_chars_with_positions(needle, haystack.chars())
+}
+
+/// Scores the needle like `score`, along with how many haystack chars
+/// between its first and last matched chars go unmatched, as a crude
+/// measure of how far it is from a substring
+///
+/// The distance is the `gaps` that `features` reports, or 0 when the needle
+/// doesn't match.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(rff::scorer::score_with_distance("abc", "axbxc").1, 2);
+/// ```
+pub fn score_with_distance(needle: &str, haystack: &str) -> (f64, usize) {
+    if !matches(needle, haystack) {
+        return (SCORE_MIN, 0);
+    }
+
+    let (score, positions) = score_with_positions(needle, haystack);
+
+    let distance = match (positions.first(), positions.last()) {
+        (Some(&first), Some(&last)) => last - first + 1 - positions.len(),
+        _ => 0
+    };
+
+    (score, distance)
+}
+
+/// Scores the needle against the concatenation of `segments`, as
+/// `score_with_positions` would, without joining them
+///
+/// Positions index chars of the concatenated haystack.
+///
+/// # Examples
+///
+/// ```
+/// use rff::scorer::{score_segmented, score_with_positions};
+///
+/// assert_eq!(score_segmented("amor", &["app/mo", "dels/", "order"]), score_with_positions("amor", "app/models/order"));
+/// ```
+pub fn score_segmented(needle: &str, segments: &[&str]) -> (f64, Vec<usize>) {
+    score_chars_with_positions(needle, segments.iter().flat_map(|segment| segment.chars()))
+}
+
+fn score_chars_with_positions<H>(needle: &str, haystack: H) -> (f64, Vec<usize>)
+    where H: Iterator<Item = char> + Clone
+{
     let needle_length = needle.chars().count();
 
     // empty needle
@@ -40,7 +216,12 @@ pub fn score_with_positions(needle: &str, haystack: &str) -> (f64, Vec<usize>) {
         return (SCORE_MIN, vec![]);
     }
 
-    let haystack_length = haystack.chars().count();
+    let haystack_length = haystack.clone().count();
+
+    // empty haystack
+    if haystack_length == 0 {
+        return (SCORE_MIN, vec![]);
+    }
 
     // perfect match
     if needle_length == haystack_length {
@@ -52,60 +233,402 @@ pub fn score_with_positions(needle: &str, haystack: &str) -> (f64, Vec<usize>) {
         return (SCORE_MIN, vec![]);
     }
 
-    let (d, m) = calculate_score(needle, needle_length, haystack, haystack_length);
-    let mut positions = vec![0 as usize; needle_length];
+    let (d, m) = calculate_score(needle, needle_length, haystack, haystack_length, &Unconstrained);
+    let positions = derive_positions(&d, &m, needle_length, haystack_length);
+
+    (m.get(needle_length - 1, haystack_length - 1), positions)
+}
+
+/// Returns the slice of the haystack covered by `positions`, as given by
+/// `score_with_positions`, if they are consecutive
+///
+/// # Examples
+///
+/// ```
+/// use rff::scorer::{contiguous_match, score_with_positions};
+///
+/// let (_, positions) = score_with_positions("foo", "foobar");
+/// assert_eq!(contiguous_match("foobar", &positions), Some("foo"));
+///
+/// let (_, positions) = score_with_positions("foo", "f_o_o");
+/// assert_eq!(contiguous_match("f_o_o", &positions), None);
+/// ```
+pub fn contiguous_match<'a>(haystack: &'a str, positions: &[usize]) -> Option<&'a str> {
+    let first = *positions.first()?;
+
+    if !positions.windows(2).all(|pair| pair[0].checked_add(1) == Some(pair[1])) {
+        return None;
+    }
+
+    let mut offsets = haystack.char_indices().map(|(offset, _)| offset).chain(Some(haystack.len()));
+    let start = offsets.nth(first)?;
+    let end = offsets.nth(positions.len() - 1)?;
+
+    Some(&haystack[start..end])
+}
+
+/// Raw measurements of the best alignment of a needle in a haystack, for
+/// feeding into other ranking models
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchFeatures {
+    /// The score, as given by `score`
+    pub score: f64,
+    /// Unmatched haystack chars between the first and last matched chars
+    pub gaps: usize,
+    /// Runs of consecutively matched chars
+    pub runs: usize,
+    /// Matched chars that start a word, path segment or camel-case hump
+    pub boundary_matches: usize,
+    /// Char index of the first matched char
+    pub first: usize,
+    /// Char index of the last matched char
+    pub last: usize,
+    /// Length of the haystack, in chars
+    pub length: usize
+}
+
+/// Measures the best alignment of the needle in the haystack, or returns
+/// `None` if the needle is empty or doesn't match
+pub fn features(needle: &str, haystack: &str) -> Option<MatchFeatures> {
+    if needle.is_empty() || !matches(needle, haystack) {
+        return None;
+    }
+
+    let (score, positions) = score_with_positions(needle, haystack);
+
+    // unreasonably large haystack
+    if positions.is_empty() {
+        return None;
+    }
+
+    let bonus = compute_bonus(haystack);
+    let first = positions[0];
+    let last = positions[positions.len() - 1];
+
+    Some(MatchFeatures {
+        score,
+        gaps: last - first + 1 - positions.len(),
+        runs: 1 + positions.windows(2).filter(|pair| pair[1] != pair[0] + 1).count(),
+        boundary_matches: positions.iter().filter(|&&p| bonus[p] != 0.0).count(),
+        first,
+        last,
+        length: bonus.len()
+    })
+}
+
+/// Describes how the best alignment of the needle in the haystack was scored,
+/// one line per needle char, for reviewing changes to the algorithm
+///
+/// Each line gives the char, the column it was matched at, the gap skipped
+/// before it, whether it scored as a consecutive match or by its bonus, and
+/// the running score. The last line adds the trailing gap and the total.
+pub fn trace(needle: &str, haystack: &str) -> String {
+    let needle_length = needle.chars().count();
+    let haystack_length = haystack.chars().count();
+
+    if needle_length == 0 || !matches(needle, haystack) {
+        return "no match\n".to_string();
+    }
+
+    if needle_length == haystack_length {
+        return "exact match\n".to_string();
+    }
+
+    if haystack_length > 1024 {
+        return "haystack too long\n".to_string();
+    }
+
+    let (d, m) = calculate_score(needle, needle_length, haystack.chars(), haystack_length, &Unconstrained);
+    let positions = derive_positions(&d, &m, needle_length, haystack_length);
+    let bonus = compute_bonus(haystack);
+    let hchars: Vec<char> = haystack.chars().collect();
+
+    let mut trace = String::new();
+    let mut next = 0;
+
+    for (i, n) in needle.chars().enumerate() {
+        let j = positions[i];
+        let score = d.get(i, j);
+        let (gap, gap_score) = if i == 0 { ("leading", SCORE_GAP_LEADING) } else { ("inner", SCORE_GAP_INNER) };
+        let skipped = j - next;
+
+        let consecutive = i > 0 && skipped == 0 && score == d.get(i - 1, j - 1) + SCORE_MATCH_CONSECUTIVE;
+        let how = if consecutive { "consecutive".to_string() } else { format!("bonus {:.1}", bonus[j]) };
+
+        write!(trace, "{:?} at {} {:?}: {} gap {} ({:.3}), {}, score {:.3}",
+               n, j, hchars[j], gap, skipped, gap_cost(skipped, gap_score), how, score).unwrap();
+
+        if i == needle_length - 1 {
+            let trailing = haystack_length - 1 - j;
+            write!(trace, ", trailing gap {} ({:.3}), total {:.3}",
+                   trailing, gap_cost(trailing, SCORE_GAP_TRAILING), m.get(i, haystack_length - 1)).unwrap();
+        }
+
+        trace.push('\n');
+        next = j + 1;
+    }
+
+    trace
+}
+
+// The cost of skipping `skipped` chars, without a negative zero for none
+fn gap_cost(skipped: usize, gap_score: f64) -> f64 {
+    if skipped == 0 { 0.0 } else { skipped as f64 * gap_score }
+}
+
+/// Scores a needle whose segments, separated by `.` or ` `, must each begin
+/// at the start of a haystack segment: the start of the haystack, or just
+/// after a `/`, `-`, `_`, ` ` or `.`
+///
+/// Returns `SCORE_MIN` and no positions if the needle can't be placed.
+///
+/// # Examples
+///
+/// ```
+/// use rff::scorer::score_anchored;
+///
+/// assert_eq!(score_anchored("s.m", "src/models").1, vec![0, 4]);
+/// assert_eq!(score_anchored("s.m", "smodels").1, vec![]);
+/// ```
+pub fn score_anchored(needle: &str, haystack: &str) -> (f64, Vec<usize>) {
+    let (needle, anchors) = parse_anchored(needle);
+
+    score_constrained(&needle, Some(&anchors), None, haystack)
+}
+
+/// Scores a needle that must abbreviate the haystack: each of its chars
+/// either starts a haystack segment or directly follows the previous char's
+/// match
+///
+/// Segments start as with `score_anchored`. Returns `SCORE_MIN` and no
+/// positions if the needle isn't an abbreviation of the haystack.
+///
+/// # Examples
+///
+/// ```
+/// use rff::scorer::score_abbreviation;
+///
+/// assert_eq!(score_abbreviation("gch", "git-checkout").1, vec![0, 4, 5]);
+/// assert_eq!(score_abbreviation("gco", "cargo").1, vec![]);
+/// ```
+pub fn score_abbreviation(needle: &str, haystack: &str) -> (f64, Vec<usize>) {
+    let anchors = vec![Anchor::StartOrFollow; needle.chars().count()];
+
+    score_constrained(needle, Some(&anchors), None, haystack)
+}
+
+/// Scores a needle in which each `*` skips any run of haystack chars for
+/// free, while the chars between them match as usual
+///
+/// Positions cover the needle's other chars. Returns `SCORE_MIN` and no
+/// positions if they can't be placed.
+///
+/// # Examples
+///
+/// ```
+/// use rff::scorer::score_with_wildcards;
+///
+/// assert_eq!(score_with_wildcards("src*rs", "src/main.rs").1, vec![0, 1, 2, 9, 10]);
+/// ```
+pub fn score_with_wildcards(needle: &str, haystack: &str) -> (f64, Vec<usize>) {
+    let (needle, free_gaps) = parse_wildcards(needle);
+
+    score_constrained(&needle, None, Some(&free_gaps), haystack)
+}
+
+fn score_constrained(needle: &str, anchors: Option<&[Anchor]>, free_gaps: Option<&[bool]>, haystack: &str) -> (f64, Vec<usize>) {
+    let needle_length = needle.chars().count();
+
+    // empty needle
+    if needle_length == 0 {
+        return (SCORE_MIN, vec![]);
+    }
+
+    let haystack_length = haystack.chars().count();
+
+    // unreasonably large haystack, or one too short to hold the needle
+    if haystack_length > 1024 || needle_length > haystack_length {
+        return (SCORE_MIN, vec![]);
+    }
+
+    let constraints = CharConstraints { anchors, free_gaps, ..CharConstraints::default() };
+    let (d, m) = calculate_score(needle, needle_length, haystack.chars(), haystack_length, &constraints);
+    let score = m.get(needle_length - 1, haystack_length - 1);
 
-    {
-        let mut match_required = false;
-        let mut j = haystack_length - 1;
+    if score == SCORE_MIN {
+        (SCORE_MIN, vec![])
+    } else if needle_length == haystack_length {
+        (SCORE_MAX, (0..needle_length).collect())
+    } else {
+        (score, derive_positions(&d, &m, needle_length, haystack_length))
+    }
+}
 
-        for i in (0..needle_length).rev() {
-            while j > (0 as usize) {
-                let last = if i > 0 && j > 0 { d.get(i - 1, j - 1) } else { 0.0 };
+// Where a needle char may match, relative to the haystack's segments
+#[derive(Clone, Copy, PartialEq)]
+enum Anchor {
+    Anywhere,
+    Start,
+    StartOrFollow
+}
 
-                let d = d.get(i, j);
-                let m = m.get(i, j);
+// Splits an anchored needle into its chars, flagging those opening a segment
+fn parse_anchored(needle: &str) -> (String, Vec<Anchor>) {
+    let mut chars = String::with_capacity(needle.len());
+    let mut anchors = Vec::with_capacity(needle.len());
+    let mut anchor = true;
 
-                if d != SCORE_MIN && (match_required || d == m) {
-                    if i > 0 && j > 0 && m == last + SCORE_MATCH_CONSECUTIVE {
-                        match_required = true;
-                    }
+    for ch in needle.chars() {
+        if ch == '.' || ch == ' ' {
+            anchor = true;
+        } else {
+            chars.push(ch);
+            anchors.push(if anchor { Anchor::Start } else { Anchor::Anywhere });
+            anchor = false;
+        }
+    }
 
-                    positions[i] = j;
+    (chars, anchors)
+}
 
-                    break;
-                }
+// Splits a wildcard needle into its literal chars, flagging the gaps that
+// a `*` stands in: the leading gap, then the gap after each char
+fn parse_wildcards(needle: &str) -> (String, Vec<bool>) {
+    let mut chars = String::with_capacity(needle.len());
+    let mut free_gaps = vec![false];
 
-                j -= 1
+    for ch in needle.chars() {
+        if ch == '*' {
+            *free_gaps.last_mut().unwrap() = true;
+        } else {
+            chars.push(ch);
+            free_gaps.push(false);
+        }
+    }
+
+    (chars, free_gaps)
+}
+
+fn derive_positions(d: &Matrix, m: &Matrix, needle_length: usize, haystack_length: usize) -> Vec<usize> {
+    derive_positions_from(d, m, needle_length, haystack_length - 1, false)
+}
+
+// Backtracks from column `j`, placing the last needle char exactly there if
+// `match_required`, or at the best column up to there otherwise
+fn derive_positions_from(d: &Matrix, m: &Matrix, needle_length: usize, mut j: usize, mut match_required: bool) -> Vec<usize> {
+    let mut positions = vec![0; needle_length];
+
+    for i in (0..needle_length).rev() {
+        loop {
+            let d_ij = d.get(i, j);
+            let m_ij = m.get(i, j);
+
+            if d_ij != SCORE_MIN && (match_required || d_ij == m_ij) {
+                // the previous char must then be the one matched just before
+                match_required = i > 0 && j > 0 && d_ij == d.get(i - 1, j - 1) + SCORE_MATCH_CONSECUTIVE;
+                positions[i] = j;
+                j = j.saturating_sub(1);
+
+                break;
             }
+
+            if j == 0 {
+                break;
+            }
+
+            j -= 1
         }
     }
 
-    (m.get(needle_length - 1, haystack_length - 1), positions)
+    positions
 }
 
-fn calculate_score(needle: &str, needle_length: usize, haystack: &str, haystack_length: usize) -> (Matrix, Matrix) {
-    let bonus = compute_bonus(haystack);
+// Restrictions on how `calculate_score` may place and score each needle char.
+// A trait rather than a struct of options, so the unconstrained scorers get a
+// copy of the loop with every check folded away.
+trait Constraints {
+    // whether `anchor` may return anything but `Anywhere`
+    const HAS_ANCHORS: bool = false;
+
+    // scales char `i`'s match and bonus contribution
+    #[inline]
+    fn weight(&self, _i: usize) -> f64 { 1.0 }
+
+    // where char `i` may match
+    #[inline]
+    fn anchor(&self, _i: usize) -> Anchor { Anchor::Anywhere }
+
+    // whether gap `g` costs nothing: gap 0 is the leading gap and gap `i + 1`
+    // the one after char `i`
+    #[inline]
+    fn free_gap(&self, _g: usize) -> bool { false }
+}
+
+// Plain fzy scoring
+struct Unconstrained;
+
+impl Constraints for Unconstrained {}
+
+#[derive(Default)]
+struct CharConstraints<'a> {
+    weights: Option<&'a [f64]>,
+    // chars anchored to a `Start` may only match at a segment start, and those
+    // anchored to a `StartOrFollow` elsewhere only right after the previous
+    // char's match
+    anchors: Option<&'a [Anchor]>,
+    free_gaps: Option<&'a [bool]>
+}
+
+impl<'a> Constraints for CharConstraints<'a> {
+    const HAS_ANCHORS: bool = true;
+
+    fn weight(&self, i: usize) -> f64 {
+        self.weights.map_or(1.0, |w| w[i])
+    }
+
+    fn anchor(&self, i: usize) -> Anchor {
+        self.anchors.map_or(Anchor::Anywhere, |a| a[i])
+    }
+
+    fn free_gap(&self, g: usize) -> bool {
+        self.free_gaps.is_some_and(|f| f[g])
+    }
+}
+
+fn calculate_score<H, C>(needle: &str, needle_length: usize, haystack: H, haystack_length: usize, constraints: &C) -> (Matrix, Matrix)
+    where H: Iterator<Item = char> + Clone, C: Constraints
+{
+    let bonus = compute_bonus_chars(haystack.clone());
+    let segment_starts = if C::HAS_ANCHORS { compute_segment_starts(haystack.clone()) } else { vec![] };
 
     let mut m = Matrix::new(needle_length, haystack_length);
     let mut d = Matrix::new(needle_length, haystack_length);
 
     for (i, n) in needle.chars().enumerate() {
         let mut prev_score = SCORE_MIN;
-        let gap_score = if i == needle_length - 1 { SCORE_GAP_TRAILING } else { SCORE_GAP_INNER };
+        let gap_score = match i {
+            _ if constraints.free_gap(i + 1) => 0.0,
+            _ if i == needle_length - 1 => SCORE_GAP_TRAILING,
+            _ => SCORE_GAP_INNER
+        };
+        let leading_gap_score = if constraints.free_gap(0) { 0.0 } else { SCORE_GAP_LEADING };
+        let weight = constraints.weight(i);
+        let anchor = constraints.anchor(i);
 
-        for (j, h) in haystack.chars().enumerate() {
-            if eq(n, h) {
-                let bonus_score = bonus[j];
+        for (j, h) in haystack.clone().enumerate() {
+            if eq(n, h) && (anchor != Anchor::Start || segment_starts[j]) {
+                let bonus_score = bonus[j] * weight;
+                let follow_only = anchor == Anchor::StartOrFollow && !segment_starts[j];
 
                 let score = match i {
-                    0 => ((j as f64) * SCORE_GAP_LEADING) + bonus_score,
+                    0 if follow_only => SCORE_MIN,
+                    0 => ((j as f64) * leading_gap_score) + bonus_score,
                     _ if j > 0 => {
                         let m = m.get(i - 1, j - 1);
                         let d = d.get(i - 1, j - 1);
 
-                        let m = m + bonus_score;
-                        let d = d + SCORE_MATCH_CONSECUTIVE;
+                        let m = if follow_only { SCORE_MIN } else { m + bonus_score };
+                        let d = d + SCORE_MATCH_CONSECUTIVE * weight;
 
                         (m).max(d)
                     },
@@ -129,18 +652,36 @@ fn calculate_score(needle: &str, needle_length: usize, haystack: &str, haystack_
 }
 
 pub fn compute_bonus(haystack: &str) -> Vec<f64> {
+    compute_bonus_chars(haystack.chars())
+}
+
+fn compute_bonus_chars<H: Iterator<Item = char>>(haystack: H) -> Vec<f64> {
     let mut last_char = '/';
 
-    let (_, len) = haystack.chars().size_hint();
-    let len = len.unwrap_or_else(|| haystack.chars().count());
+    let (min, max) = haystack.size_hint();
+    let len = max.unwrap_or(min);
+
+    haystack.fold(Vec::with_capacity(len), |mut vec, ch| {
+        let ch = fold(ch);
 
-    haystack.chars().fold(Vec::with_capacity(len), |mut vec, ch| {
         vec.push(bonus_for_char(last_char, ch));
         last_char = ch;
         vec
     })
 }
 
+fn compute_segment_starts<H: Iterator<Item = char>>(haystack: H) -> Vec<bool> {
+    let mut last_char = '/';
+
+    haystack.map(|ch| {
+        let ch = fold(ch);
+
+        let start = bonus_for_prev(last_char) != 0.0;
+        last_char = ch;
+        start
+    }).collect()
+}
+
 fn bonus_for_char(prev: char, current: char) -> f64 {
     match current {
         'a' ... 'z' | '0' ... '9' => bonus_for_prev(prev),
@@ -163,6 +704,54 @@ fn bonus_for_prev(ch: char) -> f64 {
     }
 }
 
+/// A score that can be totally ordered, for sorting, heaps and map keys
+///
+/// Ordering follows `f64::total_cmp`: `SCORE_MIN` and `SCORE_MAX` sort as the
+/// lowest and highest scores, and a NaN sorts beyond them (below `SCORE_MIN`
+/// if its sign bit is set, above `SCORE_MAX` otherwise) rather than poisoning
+/// the comparison.
+#[derive(Debug, Clone, Copy)]
+pub struct OrderedScore(pub f64);
+
+impl OrderedScore {
+    /// Returns the wrapped score
+    pub fn into_score(self) -> f64 {
+        self.0
+    }
+}
+
+impl From<f64> for OrderedScore {
+    fn from(score: f64) -> OrderedScore {
+        OrderedScore(score)
+    }
+}
+
+impl PartialEq for OrderedScore {
+    fn eq(&self, other: &OrderedScore) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OrderedScore {}
+
+impl PartialOrd for OrderedScore {
+    fn partial_cmp(&self, other: &OrderedScore) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrderedScore {
+    fn cmp(&self, other: &OrderedScore) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for OrderedScore {
+    // `total_cmp` only considers bit-identical values equal
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -205,11 +794,115 @@ mod tests {
         assert!(score("abc", " a b c    ") > score("abc", " a  b  c "));
     }
 
+    #[test]
+    fn test_score_with_lengths() {
+        assert_eq!(score_with_lengths("amor", "app/models/order", 4, 16), score("amor", "app/models/order"));
+        assert_eq!(score_with_lengths("ß", "öäßéè", 1, 5), -0.02);
+        assert_eq!(score_with_lengths("", "asdf", 0, 4), SCORE_MIN);
+        assert_eq!(score_with_lengths("a", "", 1, 0), SCORE_MIN);
+        assert_eq!(score("a", ""), SCORE_MIN);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn test_score_with_wrong_lengths() {
+        score_with_lengths("ß", "öäßéè", 2, 10);
+    }
+
     #[test]
     fn score_utf8() {
         assert_eq!(score("ß", "öäßéè"), -0.02);
     }
 
+    // Pseudo-random matching needle and haystack pairs, drawn from chars that
+    // earn every kind of bonus
+    fn random_matches(count: usize) -> Vec<(String, String)> {
+        let alphabet: Vec<char> = "aAbB./-_ ".chars().collect();
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+
+        let mut next = |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        let mut pairs = vec![];
+
+        while pairs.len() < count {
+            let needle: String = (0..1 + next(3)).map(|_| alphabet[next(alphabet.len())]).collect();
+            let haystack: String = (0..1 + next(20)).map(|_| alphabet[next(alphabet.len())]).collect();
+
+            if matches(&needle, &haystack) {
+                pairs.push((needle, haystack));
+            }
+        }
+
+        pairs
+    }
+
+    #[test]
+    fn score_agrees_with_full_recurrence() {
+        assert_eq!(score("a", "./a..b/Ab-A/A/"), score_with_positions("a", "./a..b/Ab-A/A/").0);
+
+        for (needle, haystack) in random_matches(20000) {
+            let (n, h) = (needle.chars().count(), haystack.chars().count());
+            let full = if n == h {
+                SCORE_MAX
+            } else {
+                calculate_score(&needle, n, haystack.chars(), h, &Unconstrained).1.get(n - 1, h - 1)
+            };
+
+            assert_eq!(score(&needle, &haystack), full, "{:?} in {:?}", needle, haystack);
+            assert_eq!(score_with_positions(&needle, &haystack).0, full, "{:?} in {:?}", needle, haystack);
+        }
+    }
+
+    #[test]
+    fn weighted_scores() {
+        // unit weights leave the score untouched
+        assert_eq!(score_weighted("amor", &[1.0; 4], "app/models/order"), score("amor", "app/models/order"));
+
+        // MArS beats XM/R/S, until R and S are weighted heavily
+        assert!(score("mrs", "mars") > score("mrs", "xm/r/s"));
+        assert!(score_weighted("mrs", &[1.0, 4.0, 4.0], "mars") < score_weighted("mrs", &[1.0, 4.0, 4.0], "xm/r/s"));
+
+        assert_eq!(score_weighted("a", &[1.0], ""), SCORE_MIN);
+    }
+
+    #[test]
+    #[should_panic]
+    fn weighted_scores_length_mismatch() {
+        score_weighted("amor", &[1.0, 2.0], "app/models/order");
+    }
+
+    #[test]
+    fn ordered_score() {
+        use std::collections::HashSet;
+
+        let mut scores: Vec<OrderedScore> = vec![1.5, f64::NAN, SCORE_MAX, -0.02, SCORE_MIN, -f64::NAN, 1.5].
+            into_iter().
+            map(OrderedScore::from).
+            collect();
+
+        scores.sort();
+
+        let sorted: Vec<f64> = scores.iter().map(|s| s.into_score()).collect();
+        assert!(sorted[0].is_nan() && sorted[0].is_sign_negative());
+        assert_eq!(&sorted[1..6], &[SCORE_MIN, -0.02, 1.5, 1.5, SCORE_MAX]);
+        assert!(sorted[6].is_nan() && sorted[6].is_sign_positive());
+
+        assert_eq!(OrderedScore(1.5), OrderedScore(1.5));
+        assert_eq!(OrderedScore(f64::NAN), OrderedScore(f64::NAN));
+        assert_eq!(OrderedScore(SCORE_MAX), OrderedScore(SCORE_MAX));
+        assert!(OrderedScore(SCORE_MIN) < OrderedScore(-1e300));
+        assert!(OrderedScore(f64::NAN) > OrderedScore(SCORE_MAX));
+
+        let set: HashSet<OrderedScore> = scores.into_iter().collect();
+        assert_eq!(set.len(), 6);
+    }
+
     #[test]
     fn test_compute_bonus() {
         assert_eq!(compute_bonus("a/b/c/d"), vec![0.9, 0.0, 0.9, 0.0, 0.9, 0.0, 0.9]);
@@ -259,5 +952,256 @@ mod tests {
         test_positions!("abc", "a/a/b/c/c", vec![2, 4, 6]);
         test_positions!("foo", "foo", vec![0, 1, 2]);
         test_positions!("drivers", "/path/to/drivers/file.txt", vec![9, 10, 11, 12, 13, 14, 15]);
+
+        // a matched char's column isn't reused by the char before it
+        test_positions!("foo", "x/foo", vec![2, 3, 4]);
+        test_positions!("foo", "foofoo", vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_trace() {
+        assert_eq!(trace("amor", "app/models/order"), concat!(
+            "'a' at 0 'a': leading gap 0 (0.000), bonus 0.9, score 0.900\n",
+            "'m' at 4 'm': inner gap 3 (-0.030), bonus 0.9, score 1.770\n",
+            "'o' at 11 'o': inner gap 6 (-0.060), bonus 0.9, score 2.610\n",
+            "'r' at 12 'r': inner gap 0 (0.000), consecutive, score 3.610, trailing gap 3 (-0.015), total 3.595\n"
+        ));
+
+        assert_eq!(trace("amor", "xyz"), "no match\n");
+        assert_eq!(trace("", "xyz"), "no match\n");
+        assert_eq!(trace("xyz", "XYZ"), "exact match\n");
+    }
+
+    #[test]
+    fn test_features() {
+        assert_eq!(features("amor", "app/models/order"), Some(MatchFeatures {
+            score: score("amor", "app/models/order"),
+            gaps: 9,
+            runs: 3,
+            boundary_matches: 3,
+            first: 0,
+            last: 12,
+            length: 16
+        }));
+
+        let features = features("drivers", "/path/to/drivers/file.txt").unwrap();
+        assert_eq!((features.gaps, features.runs, features.boundary_matches), (0, 1, 1));
+        assert_eq!((features.first, features.last, features.length), (9, 15, 25));
+    }
+
+    #[test]
+    fn test_features_no_match() {
+        assert_eq!(features("", "app/models/order"), None);
+        assert_eq!(features("xyz", "app/models/order"), None);
+        assert_eq!(features("x", &"X".repeat(1025)), None);
+    }
+
+    #[test]
+    fn segmented() {
+        macro_rules! test_segmented {
+            ($needle:expr, $segments:expr) => {
+                let segments: &[&str] = &$segments;
+                assert_eq!(score_segmented($needle, segments), score_with_positions($needle, &segments.concat()));
+            }
+        }
+
+        test_segmented!("amor", ["app/", "models/", "order"]);
+        test_segmented!("amor", ["a", "pp/mo", "", "dels/ord", "er"]);
+        test_segmented!("drivers", ["/path/to/dr", "ivers/file.txt"]);
+        test_segmented!("ß", ["öä", "ßéè"]);
+        test_segmented!("foo", ["f", "o", "o"]);
+        test_segmented!("", ["foo"]);
+
+        // nothing to match against
+        assert_eq!(score_segmented("foo", &[]), (SCORE_MIN, vec![]));
+        assert_eq!(score_segmented("foo", &[""]), (SCORE_MIN, vec![]));
+        assert_eq!(score_segmented("foo", &["", ""]), (SCORE_MIN, vec![]));
+        assert_eq!(score_with_positions("foo", ""), (SCORE_MIN, vec![]));
+
+        // positions are in the concatenated haystack's coordinates
+        assert_eq!(score_segmented("mo", &["app/", "models"]).1, vec![4, 5]);
+    }
+
+    #[test]
+    fn anchored() {
+        // each anchored char lands on a segment start
+        assert_eq!(score_anchored("s.m", "src/models").1, vec![0, 4]);
+        assert_eq!(score_anchored("s m", "src/models").1, vec![0, 4]);
+        assert_eq!(score_anchored("sr.mo", "src/models").1, vec![0, 1, 4, 5]);
+        assert_eq!(score_anchored("a.m.o", "app/models/order").1, vec![0, 4, 11]);
+        assert!(score_anchored("s.m", "src/models").0 > SCORE_MIN);
+
+        // an anchored char can't be placed mid-segment
+        assert_eq!(score_anchored("s.m", "smodels"), (SCORE_MIN, vec![]));
+        assert_eq!(score_anchored("m", "app/xmodels"), (SCORE_MIN, vec![]));
+
+        assert_eq!(score_anchored("s.m", "sm"), (SCORE_MIN, vec![]));
+        assert_eq!(score_anchored("s.m", "s/m").1, vec![0, 2]);
+        assert_eq!(score_anchored("", "src/models"), (SCORE_MIN, vec![]));
+        assert_eq!(score_anchored("..", "src/models"), (SCORE_MIN, vec![]));
+    }
+
+    #[test]
+    fn last_row() {
+        let best = |row: &[f64]| row.iter().cloned().fold(SCORE_MIN, f64::max);
+
+        let cases = [
+            ("foo", "foofoo"),
+            ("amor", "app/models/order"),
+            ("ab", "a_b_ab"),
+            ("abc", "abcabcabc"),
+            ("test", "t/e/s/t"),
+            ("foo", "foo")
+        ];
+
+        for &(needle, haystack) in cases.iter() {
+            let row = last_row_scores(needle, haystack);
+
+            assert_eq!(row.len(), haystack.chars().count());
+            assert_eq!(best(&row), score(needle, haystack), "{:?} in {:?}", needle, haystack);
+        }
+
+        assert_eq!(best(&last_row_scores("a", "./a..b/Ab-A/A/")), score("a", "./a..b/Ab-A/A/"));
+
+        for (needle, haystack) in random_matches(20000) {
+            assert_eq!(best(&last_row_scores(&needle, &haystack)), score(&needle, &haystack), "{:?} in {:?}", needle, haystack);
+        }
+
+        // one entry per alignment endpoint
+        let row = last_row_scores("foo", "foofoo");
+        assert_eq!(row.iter().filter(|&&s| s != SCORE_MIN).count(), 3);
+        assert!(row[2] > row[5] && row[5] > row[4]);
+        assert_eq!(row[0], SCORE_MIN);
+
+        assert_eq!(last_row_scores("", "foo"), vec![SCORE_MIN; 3]);
+        assert!(last_row_scores("xy", "foo").iter().all(|&s| s == SCORE_MIN));
+        assert_eq!(last_row_scores("abc", "xyz"), vec![SCORE_MIN; 3]);
+        assert_eq!(last_row_scores("ABC", "abc"), vec![SCORE_MIN, SCORE_MIN, SCORE_MAX]);
+    }
+
+    #[test]
+    fn positions_bidi() {
+        // right-to-left text doesn't reorder positions
+        assert_eq!(score_with_positions("שלום", "docs/שלום.md").1, vec![5, 6, 7, 8]);
+        assert_eq!(score_with_positions("سm", "سلام/main").1, vec![0, 5]);
+        assert_eq!(score_with_positions("aלb", "a-שלום-b").1, vec![0, 3, 7]);
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(score_with_distance("abc", "axbxc"), (score("abc", "axbxc"), 2));
+        assert_eq!(score_with_distance("abc", "xxabcxx").1, 0);
+        assert_eq!(score_with_distance("abc", "abc"), (SCORE_MAX, 0));
+        assert_eq!(score_with_distance("", "abc"), (SCORE_MIN, 0));
+
+        // a non-match has no span to measure
+        assert_eq!(score_with_distance("zz", "abc"), (SCORE_MIN, 0));
+        assert_eq!(score_with_distance("amor", "README.md"), (SCORE_MIN, 0));
+        assert_eq!(score_with_distance("abc", "xyz"), (SCORE_MIN, 0));
+
+        for &(needle, haystack) in [("amor", "app/models/order"), ("test", "t/e/s/t"), ("ab", "a   b")].iter() {
+            assert_eq!(score_with_distance(needle, haystack).1, features(needle, haystack).unwrap().gaps);
+        }
+    }
+
+    #[test]
+    fn ending_at() {
+        assert_eq!(positions_ending_at("foo", "foofoo", 5), vec![3, 4, 5]);
+        assert_eq!(positions_ending_at("foo", "foofoo", 2), vec![0, 1, 2]);
+        assert_eq!(positions_ending_at("foo", "foofoo", 4).last(), Some(&4));
+        assert_eq!(positions_ending_at("amor", "app/models/order", 12), vec![0, 4, 11, 12]);
+        assert_eq!(positions_ending_at("amor", "app/models/order", 15).last(), Some(&15));
+
+        // the best endpoint gives the best alignment's positions
+        for &(needle, haystack) in [("amor", "app/models/order"), ("ab", "a_b_ab"), ("test", "t/e/s/t")].iter() {
+            let row = last_row_scores(needle, haystack);
+            let best = (0..row.len()).max_by_key(|&j| OrderedScore(row[j])).unwrap();
+
+            assert_eq!(positions_ending_at(needle, haystack, best), score_with_positions(needle, haystack).1);
+        }
+
+        // nothing ends on a column that can't match the last char
+        assert_eq!(positions_ending_at("foo", "foofoo", 3), vec![]);
+        assert_eq!(positions_ending_at("foo", "foofoo", 1), vec![]);
+        assert_eq!(positions_ending_at("foo", "foofoo", 6), vec![]);
+        assert_eq!(positions_ending_at("", "foofoo", 2), vec![]);
+    }
+
+    #[test]
+    fn contiguous() {
+        let slice = |needle, haystack| contiguous_match(haystack, &score_with_positions(needle, haystack).1);
+
+        assert_eq!(slice("foo", "foobar"), Some("foo"));
+        assert_eq!(slice("bar", "foobar"), Some("bar"));
+        assert_eq!(slice("foo", "foo"), Some("foo"));
+        assert_eq!(slice("mod", "app/models"), Some("mod"));
+        assert_eq!(slice("山田", "💣山田.txt"), Some("山田"));
+
+        assert_eq!(slice("foo", "f_o_o"), None);
+        assert_eq!(slice("amo", "app/models"), None);
+        assert_eq!(slice("", "foo"), None);
+
+        // positions past the haystack's end
+        assert_eq!(contiguous_match("ab", &[1, 2]), None);
+        assert_eq!(contiguous_match("ab", &[usize::MAX]), None);
+
+        // positions out of order
+        assert_eq!(contiguous_match("abc", &[2, 0]), None);
+        assert_eq!(contiguous_match("abc", &[0, 2, 2]), None);
+        assert_eq!(contiguous_match("abc", &[1, 1]), None);
+    }
+
+    #[test]
+    fn wildcards() {
+        assert_eq!(score_with_wildcards("src*rs", "src/main.rs").1, vec![0, 1, 2, 9, 10]);
+
+        // skipping across a `*` is free, but gaps elsewhere still cost
+        assert_eq!(score_with_wildcards("src*rs", "src/main.rs").0, score_with_wildcards("src*rs", "src.rs").0);
+        assert_eq!(score_with_wildcards("*rs", "src/main.rs").0, score_with_wildcards("*rs", "x.rs").0);
+        assert_eq!(score_with_wildcards("src*", "src/main.rs").0, score_with_wildcards("src*", "src/").0);
+        assert!(score_with_wildcards("src*rs", "src/main.xrs").0 < score_with_wildcards("src*rs", "src/main.rs").0);
+        assert!(score_with_wildcards("src*rs", "src/main.rs").0 > score("srcrs", "src/main.rs"));
+
+        // without a `*`, it scores like any other needle
+        assert_eq!(score_with_wildcards("amor", "app/models/order"), score_with_positions("amor", "app/models/order"));
+        assert_eq!(score_with_wildcards("a**b", "axxb"), score_with_wildcards("a*b", "axxb"));
+
+        assert_eq!(score_with_wildcards("src*rs", "rs/src"), (SCORE_MIN, vec![]));
+        assert_eq!(score_with_wildcards("*", "src"), (SCORE_MIN, vec![]));
+        assert_eq!(score_with_wildcards("", "src"), (SCORE_MIN, vec![]));
+    }
+
+    #[test]
+    fn abbreviation() {
+        // each char starts a segment or continues the previous match
+        assert_eq!(score_abbreviation("gch", "git-checkout").1, vec![0, 4, 5]);
+        assert_eq!(score_abbreviation("gich", "git-checkout").1, vec![0, 1, 4, 5]);
+        assert_eq!(score_abbreviation("amo", "app/models/order").1, vec![0, 4, 5]);
+        assert_eq!(score_abbreviation("amor", "app/models/order").1, vec![0, 4, 11, 12]);
+        assert_eq!(score_abbreviation("ab", "ab"), (SCORE_MAX, vec![0, 1]));
+        assert!(score_abbreviation("gch", "git-checkout").0 > SCORE_MIN);
+
+        // a plain subsequence isn't enough
+        assert_eq!(score_abbreviation("gco", "cargo"), (SCORE_MIN, vec![]));
+        assert_eq!(score_abbreviation("gco", "git-checkout"), (SCORE_MIN, vec![]));
+        assert_eq!(score_abbreviation("gt", "git"), (SCORE_MIN, vec![]));
+        assert_eq!(score_abbreviation("", "git"), (SCORE_MIN, vec![]));
+    }
+
+    #[test]
+    #[cfg(feature = "punctuation")]
+    fn positions_punctuation() {
+        // positions index the original haystack, and folded dashes are word breaks
+        assert_eq!(score_with_positions("a-b", "xa–b"), score_with_positions("a-b", "xa-b"));
+        assert_eq!(score_with_positions("a-b", "xa–b").1, vec![1, 2, 3]);
+        assert_eq!(compute_bonus("a–b"), compute_bonus("a-b"));
+    }
+
+    #[test]
+    #[cfg(feature = "fullwidth")]
+    fn positions_fullwidth() {
+        // positions index the original, full-width haystack
+        assert_eq!(score_with_positions("amo", "ａｐｐ／ｍｏｄｅｌｓ／ｆｏｏ"), score_with_positions("amo", "app/models/foo"));
+        assert_eq!(score_with_positions("amo", "ａｐｐ／ｍｏｄｅｌｓ／ｆｏｏ").1, vec![0, 4, 5]);
     }
 }